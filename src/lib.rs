@@ -7,7 +7,12 @@ use esp_idf_svc::hal::{
 };
 use lazy_static::lazy_static;
 use port_expander::{dev::pcal6416a, Pcal6416a};
-use std::sync::{Arc, Mutex};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+pub mod postmortem;
 
 const DEVICE_ADDRESS: u8 = 0x48;
 const BRIGHTNESS_ADDRESS: u8 = 0x2E; // 0x5C >> 1;
@@ -18,6 +23,8 @@ type PortMutexInkplate<'a> = Mutex<pcal6416a::Driver<I2cBus<'a>>>;
 pub struct Inkplate {
     i2c: Arc<Mutex<I2cDriver<'static>>>,
     pins: Pcal6416a<PortMutexInkplate<'static>>,
+    brightness: u8,
+    frontlight: bool,
 }
 
 lazy_static! {
@@ -35,6 +42,8 @@ lazy_static! {
         Arc::new(Mutex::new(Inkplate {
             i2c: Arc::clone(&I2C_MUTEX),
             pins: Pcal6416a::with_mutex(i2c_bus, false),
+            brightness: 0,
+            frontlight: false,
         }))
     };
 }
@@ -75,6 +84,9 @@ impl Inkplate {
             .unwrap()
             .set_low()
             .unwrap();
+
+        // The digipot keeps its level across ESP32-only resets, start from a known one
+        self.write_brightness(0);
     }
 
     pub fn eink_on(&mut self) {
@@ -102,14 +114,52 @@ impl Inkplate {
     }
 
     pub fn set_brightness(&mut self, brightness: u8) {
+        self.write_brightness(brightness);
+        self.frontlight_on();
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    // Ramps the digipot one step at a time from the current brightness (or
+    // from dark if the light is off) to `brightness`, spreading the steps
+    // evenly over `duration_ms`.
+    pub fn fade_brightness(&mut self, brightness: u8, duration_ms: u32) {
+        let target = brightness & 0b00111111;
+        if !self.frontlight {
+            self.write_brightness(0);
+        }
         self.frontlight_on();
+        let steps = self.brightness.abs_diff(target) as u32;
+        if steps == 0 {
+            return;
+        }
+
+        let delay: Delay = Default::default();
+        let start = Instant::now();
+        for step in 1..=steps {
+            let next = if self.brightness < target {
+                self.brightness + 1
+            } else {
+                self.brightness - 1
+            };
+            self.write_brightness(next);
+
+            // Pace against the start time so I2C time and rounding don't add up
+            let due = Duration::from_millis(duration_ms as u64 * step as u64 / steps as u64);
+            if let Some(wait) = due.checked_sub(start.elapsed()) {
+                delay.delay_ms(wait.as_millis() as u32);
+            }
+        }
+    }
+
+    fn write_brightness(&mut self, brightness: u8) {
+        let brightness = brightness & 0b00111111;
         let mut i2c = self.i2c.lock().unwrap();
-        i2c.write(
-            BRIGHTNESS_ADDRESS,
-            &[0x00, 63 - (brightness & 0b00111111)],
-            BLOCK,
-        )
-        .unwrap();
+        i2c.write(BRIGHTNESS_ADDRESS, &[0x00, 63 - brightness], BLOCK)
+            .unwrap();
+        self.brightness = brightness;
     }
 
     // #define FRONTLIGHT_EN 10
@@ -121,6 +171,7 @@ impl Inkplate {
             .unwrap()
             .set_high()
             .unwrap();
+        self.frontlight = true;
     }
 
     pub fn frontlight_off(&mut self) {
@@ -131,6 +182,7 @@ impl Inkplate {
             .unwrap()
             .set_low()
             .unwrap();
+        self.frontlight = false;
     }
 
     fn read_power_good(&self) -> u8 {
@@ -1,6 +1,9 @@
 use embassy_time::{Duration, Timer};
 use esp_idf_svc::hal::delay::Delay;
-use meditamer::Inkplate;
+use meditamer::{
+    postmortem::{self, ResetClass},
+    Inkplate,
+};
 // see https://pg3.dev/post/13
 
 // default Inkplate Arduino library uses I2C to set up the display
@@ -13,6 +16,28 @@ fn main() {
     esp_idf_svc::sys::link_patches();
     esp_idf_svc::log::EspLogger::initialize_default();
 
+    let reset = ResetClass::current();
+    if reset.is_abnormal() {
+        log::warn!("Reset reason: {:?}", reset);
+    } else {
+        log::info!("Reset reason: {:?}", reset);
+    }
+    if let Some(report) = postmortem::install_panic_hook() {
+        log::warn!(
+            "Previous boot panicked in task '{}': {}",
+            report.task,
+            report.message
+        );
+        if !report.backtrace.is_empty() {
+            let backtrace: Vec<String> = report
+                .backtrace
+                .iter()
+                .map(|pc| format!("{:#010x}", pc))
+                .collect();
+            log::warn!("Panic backtrace: {}", backtrace.join(" "));
+        }
+    }
+
     log::info!("Starting");
 
     let inkplate_instance = Inkplate::instance();
@@ -21,11 +46,12 @@ fn main() {
     inkplate.init();
 
     log::info!("Initialization complete, turning on the lights..");
-    inkplate.set_brightness(32);
+    inkplate.fade_brightness(32, 500);
 
     let delay: Delay = Default::default();
     delay.delay_ms(1500);
 
+    inkplate.fade_brightness(0, 500);
     inkplate.frontlight_off();
     log::info!("Lights off");
     //block_on(async_main());
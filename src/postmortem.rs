@@ -0,0 +1,244 @@
+use esp_idf_svc::{
+    hal::reset::ResetReason,
+    sys::{esp_backtrace_frame_t, esp_backtrace_get_next_frame, esp_backtrace_get_start},
+};
+use std::{
+    fmt::{self, Write},
+    mem::MaybeUninit,
+    panic,
+    ptr::{self, addr_of, addr_of_mut},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+// Panic record kept in RTC slow memory: `.rtc_noinit` is not cleared on
+// software/panic resets, so the hook can leave a note for the next boot.
+// After a power-on the contents are garbage, hence the magic word.
+const RECORD_MAGIC: u32 = 0x504e_4943; // "PNIC"
+const MESSAGE_LEN: usize = 240;
+const TASK_LEN: usize = 16;
+const BACKTRACE_LEN: usize = 32;
+// Frames between the panic site and esp_backtrace_get_start: capture_backtrace,
+// store and the hook closure, then std's rust_panic_with_hook,
+// __rust_end_short_backtrace, rust_begin_unwind and core's panic_fmt. Errs on
+// the low side (std may keep a closure out of line): an extra std frame costs a
+// slot, while skipping one too many would lose the panic site itself.
+const SKIP_FRAMES: usize = 7;
+
+// Coarse grouping of the ESP32 reset reason, for triaging field resets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResetClass {
+    PowerOn,
+    Brownout,
+    Watchdog,
+    Panic,
+    Software,
+    DeepSleep,
+    Other,
+}
+
+impl ResetClass {
+    pub fn current() -> Self {
+        ResetReason::get().into()
+    }
+
+    // Resets that point at a problem rather than a regular power cycle,
+    // restart or wakeup.
+    pub fn is_abnormal(self) -> bool {
+        matches!(
+            self,
+            ResetClass::Brownout | ResetClass::Watchdog | ResetClass::Panic
+        )
+    }
+}
+
+impl From<ResetReason> for ResetClass {
+    fn from(reason: ResetReason) -> Self {
+        match reason {
+            ResetReason::PowerOn => ResetClass::PowerOn,
+            ResetReason::Brownout => ResetClass::Brownout,
+            ResetReason::Watchdog | ResetReason::InterruptWatchdog | ResetReason::TaskWatchdog => {
+                ResetClass::Watchdog
+            }
+            ResetReason::Panic => ResetClass::Panic,
+            ResetReason::Software => ResetClass::Software,
+            ResetReason::DeepSleep => ResetClass::DeepSleep,
+            _ => ResetClass::Other,
+        }
+    }
+}
+
+#[repr(C)]
+struct PanicRecord {
+    magic: u32,
+    message_len: u32,
+    task_len: u32,
+    backtrace_len: u32,
+    message: [u8; MESSAGE_LEN],
+    task: [u8; TASK_LEN],
+    backtrace: [u32; BACKTRACE_LEN],
+}
+
+#[link_section = ".rtc_noinit"]
+static mut PANIC_RECORD: MaybeUninit<PanicRecord> = MaybeUninit::uninit();
+
+// Set once the panic hook is installed; the record is only read before that.
+static ARMED: AtomicBool = AtomicBool::new(false);
+// Set by the first panicking thread; later or concurrent panics leave the record alone.
+static RECORDING: AtomicBool = AtomicBool::new(false);
+
+pub struct PanicReport {
+    pub task: String,
+    pub message: String,
+    // Program counters from the panicking call stack, innermost first, with
+    // the hook and std panic frames skipped.
+    pub backtrace: Vec<u32>,
+}
+
+// Takes the record left by a panic on the previous boot, then installs a panic
+// hook that stores the panic message, thread name and backtrace addresses
+// before handing over to the default hook (which prints and aborts).
+//
+// Only the first call does anything; later calls return None.
+pub fn install_panic_hook() -> Option<PanicReport> {
+    if ARMED
+        .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+        .is_err()
+    {
+        return None;
+    }
+    // SAFETY: winning ARMED makes this the only reader, and the hook, the only
+    // writer, is not installed until after the read.
+    let report = unsafe { take_report() };
+
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let task = std::thread::current();
+        store(task.name().unwrap_or("?"), info);
+        default_hook(info);
+    }));
+    report
+}
+
+// Returns the record left by a panic on the previous boot, if any, and clears it.
+//
+// SAFETY: the caller must have exclusive access to the record, i.e. the panic
+// hook must not be installed yet (see install_panic_hook).
+unsafe fn take_report() -> Option<PanicReport> {
+    // After power-on the record is uninitialised, so only the magic word is
+    // read, with a volatile load through a raw pointer, and no reference is
+    // formed until it matches. A matching magic means `store` zeroed and
+    // filled the whole record.
+    unsafe {
+        let record = addr_of_mut!(PANIC_RECORD).cast::<PanicRecord>();
+        if ptr::read_volatile(addr_of!((*record).magic)) != RECORD_MAGIC {
+            return None;
+        }
+        ptr::write_volatile(addr_of_mut!((*record).magic), 0);
+        let record = &*record;
+
+        let message_len = (record.message_len as usize).min(MESSAGE_LEN);
+        let task_len = (record.task_len as usize).min(TASK_LEN);
+        let backtrace_len = (record.backtrace_len as usize).min(BACKTRACE_LEN);
+        Some(PanicReport {
+            task: String::from_utf8_lossy(&record.task[..task_len]).into_owned(),
+            message: String::from_utf8_lossy(&record.message[..message_len]).into_owned(),
+            backtrace: record.backtrace[..backtrace_len].to_vec(),
+        })
+    }
+}
+
+// Kept out of line so the number of frames to skip stays fixed.
+#[inline(never)]
+fn store(task: &str, info: &dyn fmt::Display) {
+    if RECORDING
+        .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+        .is_err()
+    {
+        return;
+    }
+
+    // SAFETY: several threads can run the panic hook at once, but only the one
+    // that won RECORDING gets here. The hook is installed by
+    // install_panic_hook() after its one take_report() call has returned, and
+    // ARMED prevents any further reads, so nothing else touches the record.
+    unsafe {
+        let record = addr_of_mut!(PANIC_RECORD).cast::<PanicRecord>();
+        ptr::write_bytes(record, 0, 1);
+        let record = &mut *record;
+
+        let mut message = Truncating::new(&mut record.message);
+        let _ = write!(message, "{}", info);
+        record.message_len = message.len as u32;
+
+        let mut name = Truncating::new(&mut record.task);
+        let _ = name.write_str(task);
+        record.task_len = name.len as u32;
+
+        record.backtrace_len = capture_backtrace(&mut record.backtrace) as u32;
+
+        record.magic = RECORD_MAGIC;
+    }
+}
+
+// Walks the Xtensa call stack from the current frame, skipping the first
+// SKIP_FRAMES frames and storing up to `pcs.len()` program counters after
+// that. Returns the number stored.
+#[inline(never)]
+fn capture_backtrace(pcs: &mut [u32]) -> usize {
+    // SAFETY: esp_backtrace_frame_t is plain data, all-zero is a valid value;
+    // the esp_backtrace_* helpers only walk the current task's stack.
+    unsafe {
+        let mut frame: esp_backtrace_frame_t = std::mem::zeroed();
+        esp_backtrace_get_start(&mut frame.pc, &mut frame.sp, &mut frame.next_pc);
+
+        let mut depth = 0;
+        let mut len = 0;
+        while len < pcs.len() {
+            if depth >= SKIP_FRAMES {
+                pcs[len] = stack_pc(frame.pc);
+                len += 1;
+            }
+            depth += 1;
+            if frame.next_pc == 0 || !esp_backtrace_get_next_frame(&mut frame) {
+                break;
+            }
+        }
+        len
+    }
+}
+
+// Same as esp_cpu_process_stack_pc(): return addresses carry the call window
+// size in the top two bits, and point past the 3-byte call instruction.
+fn stack_pc(pc: u32) -> u32 {
+    let pc = if pc & 0x8000_0000 != 0 {
+        (pc & 0x3fff_ffff) | 0x4000_0000
+    } else {
+        pc
+    };
+    pc.wrapping_sub(3)
+}
+
+// fmt::Write into a fixed buffer, silently dropping whatever does not fit
+// (cut on a char boundary so the record stays valid UTF-8).
+struct Truncating<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> Truncating<'a> {
+    fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+}
+
+impl Write for Truncating<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut n = s.len().min(self.buf.len() - self.len);
+        while !s.is_char_boundary(n) {
+            n -= 1;
+        }
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}